@@ -0,0 +1,130 @@
+use num::{BigInt, BigRational, Zero, One, Signed, Integer};
+use num::bigint::ToBigInt;
+
+use super::parser::{Expr, ExprF};
+
+// A builtin is dispatched once every argument has already reduced to a plain
+// number; `simplify_application` is responsible for that check, so these
+// functions can assume `args` holds nothing but `Expr::Number`s.
+pub type Builtin = fn(&[Expr]) -> Result<Expr, String>;
+
+/// The functions seeded into every fresh `Scope`.
+pub fn stdlib() -> Vec<(&'static str, Builtin)> {
+    vec![
+        ("abs", abs),
+        ("floor", floor),
+        ("ceil", ceil),
+        ("gcd", gcd),
+        ("factorial", factorial),
+        ("sqrt", sqrt),
+    ]
+}
+
+fn arg(args: &[Expr], i: usize) -> Result<&BigRational, String> {
+    match args.get(i).map(|e| e.node()) {
+        Some(&ExprF::Number(ref n)) => Ok(n),
+        _ => Err(format!("Expected a number argument"))
+    }
+}
+
+fn abs(args: &[Expr]) -> Result<Expr, String> {
+    if args.len() != 1 {
+        return Err(format!("abs expects 1 argument, got {}", args.len()));
+    }
+
+    Ok(Expr::number(arg(args, 0)?.abs()))
+}
+
+fn floor(args: &[Expr]) -> Result<Expr, String> {
+    if args.len() != 1 {
+        return Err(format!("floor expects 1 argument, got {}", args.len()));
+    }
+
+    Ok(Expr::number(arg(args, 0)?.floor()))
+}
+
+fn ceil(args: &[Expr]) -> Result<Expr, String> {
+    if args.len() != 1 {
+        return Err(format!("ceil expects 1 argument, got {}", args.len()));
+    }
+
+    Ok(Expr::number(arg(args, 0)?.ceil()))
+}
+
+fn gcd(args: &[Expr]) -> Result<Expr, String> {
+    if args.len() != 2 {
+        return Err(format!("gcd expects 2 arguments, got {}", args.len()));
+    }
+
+    let a = arg(args, 0)?;
+    let b = arg(args, 1)?;
+
+    if !a.is_integer() || !b.is_integer() {
+        return Err(format!("Cannot take the gcd of non-integer values {} and {}", a, b));
+    }
+
+    Ok(Expr::number(BigRational::from_integer(a.numer().gcd(b.numer()))))
+}
+
+fn factorial(args: &[Expr]) -> Result<Expr, String> {
+    if args.len() != 1 {
+        return Err(format!("factorial expects 1 argument, got {}", args.len()));
+    }
+
+    let n = arg(args, 0)?;
+
+    if !n.is_integer() || n.is_negative() {
+        return Err(format!("Cannot take the factorial of {}", n));
+    }
+
+    let n = n.to_integer();
+    let mut result = BigInt::one();
+    let mut i = BigInt::one();
+
+    while i <= n {
+        result = result * &i;
+        i = i + BigInt::one();
+    }
+
+    Ok(Expr::number(BigRational::from_integer(result)))
+}
+
+fn sqrt(args: &[Expr]) -> Result<Expr, String> {
+    if args.len() != 1 {
+        return Err(format!("sqrt expects 1 argument, got {}", args.len()));
+    }
+
+    let n = arg(args, 0)?;
+
+    if n.is_negative() {
+        return Err(format!("Cannot take the square root of negative number {}", n));
+    }
+
+    let numer_root = isqrt(n.numer());
+    let denom_root = isqrt(n.denom());
+
+    if &numer_root * &numer_root == *n.numer() && &denom_root * &denom_root == *n.denom() {
+        Ok(Expr::number(BigRational::new(numer_root, denom_root)))
+    } else {
+        Err(format!("{} is not a perfect square", n))
+    }
+}
+
+// Integer square root via Newton's method, staying in exact BigInt arithmetic
+// so `sqrt` can tell a perfect square from one that merely looks close.
+fn isqrt(n: &BigInt) -> BigInt {
+    if n.is_zero() {
+        return BigInt::zero();
+    }
+
+    let two = 2.to_bigint().unwrap();
+    let mut x = n.clone();
+    let mut y = (&x + BigInt::one()).div_rem(&two).0;
+
+    while y < x {
+        x = y.clone();
+        y = (&x + n.div_rem(&x).0).div_rem(&two).0;
+    }
+
+    x
+}
@@ -1,14 +1,20 @@
 use std::collections::{HashMap, HashSet};
 
 use super::parser::Expr;
+use super::builtins::{self, Builtin};
 
 pub struct Scope {
-	vars: HashMap<String, Expr>
+	vars: HashMap<String, Expr>,
+	builtins: HashMap<String, Builtin>
 }
 
 impl Scope {
 	pub fn new() -> Self {
-		Scope { vars: HashMap::new() }
+		let builtins = builtins::stdlib().into_iter()
+			.map(|(name, f)| (String::from(name), f))
+			.collect();
+
+		Scope { vars: HashMap::new(), builtins }
 	}
 
 }
@@ -40,6 +46,10 @@ impl<'a> Context<'a> {
 		}
 	}
 
+	pub fn builtin(&self, name: &String) -> Option<Builtin> {
+		self.scope.builtins.get(name).cloned()
+	}
+
 	pub fn evaluate<'b>(&'b mut self, name: String) -> Context<'b> {
 		let mut evaluating = self.evaluating.clone();
 		evaluating.insert(name);
@@ -1,60 +1,41 @@
-use super::parser::{Expr, Op};
+use super::parser::{Expr, ExprF, Op};
 use num::{pow, Zero, One, Signed, ToPrimitive, FromPrimitive, BigRational};
 use num::rational::Ratio;
 use num::bigint::ToBigInt;
 
 use super::context::Context;
 
-use std::collections::HashSet;
-use std::mem::swap;
-
 pub fn evaluate(expression: Expr, context: &mut Context) -> Result<Expr, String> {
     let expression = normalize(&expression);
-    let expression = simplify(&expression);
-
-    Ok(expression)
+    simplify(&expression, context)
 }
 
 fn normalize(expr: &Expr) -> Expr {
+    expr.fold(&mut normalize_node)
+}
+
+fn normalize_node(node: ExprF<Expr>) -> Expr {
     let neg = BigRational::from_integer(FromPrimitive::from_i64(-1).unwrap());
 
-    match expr {
-        &Expr::BinaryExpr(ref lhs, op, ref rhs) => {
-            let mut lhs = normalize(lhs);
+    match node {
+        ExprF::BinaryExpr(lhs, op, rhs) => {
+            let mut lhs = lhs;
             let mut op = op;
-            let mut rhs = normalize(rhs);
+            let mut rhs = rhs;
 
             if op == Op::Adjacent {
-                // TODO: Turn (function a) into Application(function, a)
                 // TODO: Turn (a unit) into Measure(a, unit)
                 op = Op::Multiply;
             }
 
             if op == Op::Subtract {
                 // (a - b) => (a + (-1 * b))
-                /*let zero = if let Expr::Number(ref a) = lhs {
-                    a.is_zero()
-                } else {
-                    false
-                };
-
-                if zero {
-                    lhs = Expr::Number(neg);
-                    op = Op::Multiply;
-                } else {*/
-                    op = Op::Add;
-                    rhs = Expr::BinaryExpr(
-                        Box::new(Expr::Number(neg)),
-                        Op::Multiply,
-                        Box::new(rhs));
-                //}
+                op = Op::Add;
+                rhs = Expr::binary(Expr::number(neg), Op::Multiply, rhs);
             } else if op == Op::Divide {
                 // (a / b) => (a * (b ^ -1))
                 op = Op::Multiply;
-                rhs = Expr::BinaryExpr(
-                    Box::new(rhs),
-                    Op::Exponent,
-                    Box::new(Expr::Number(neg)));
+                rhs = Expr::binary(rhs, Op::Exponent, Expr::number(neg));
             }
 
             if op == Op::Multiply || op == Op::Add {
@@ -63,61 +44,206 @@ fn normalize(expr: &Expr) -> Expr {
                 rhs = new_rhs;
             }
 
-            Expr::BinaryExpr(Box::new(lhs), op, Box::new(rhs))
+            Expr::binary(lhs, op, rhs)
         },
-        e => e.clone()
+        other => Expr::new(other)
     }
 }
 
-/*fn apply_associative(op: Op, lhs: Expr, rhs: Expr) -> (Expr, Expr) {
-    match rhs {
-        Expr::BinaryExpr(ref b, ref inner_op, ref c) if inner_op == &op => {
-            let (a, b) = apply_associative(op, lhs, b.as_ref().clone());
-            (Expr::BinaryExpr(Box::new(a), op, Box::new(b)), c.as_ref().clone())
-        },
-        _ => (lhs, rhs)
-    }
-}*/
 fn apply_associative(op: Op, lhs: Expr, rhs: Expr) -> (Expr, Expr) {
-    match lhs {
-        Expr::BinaryExpr(ref a, ref inner_op, ref b) if inner_op == &op => {
-            let (b, rhs) = apply_associative(op, b.as_ref().clone(), rhs);
-            (a.as_ref().clone(), Expr::BinaryExpr(Box::new(b), op, Box::new(rhs)))
+    match lhs.into_node() {
+        ExprF::BinaryExpr(a, inner_op, b) if inner_op == op => {
+            let (b, rhs) = apply_associative(op, b, rhs);
+            (a, Expr::binary(b, op, rhs))
         },
-        _ => (lhs, rhs)
+        other => (Expr::new(other), rhs)
     }
 }
 
-// Assumes that `expr` has already been normalized via `normalize()`
-fn simplify(expr: &Expr) -> Expr {
-    let new_expr = simplify_inner(expr);
+// Assumes that `expr` has already been normalized via `normalize()`.
+// Drives a single bottom-up pass over the tree: `simplify_node` is applied to
+// every node with its children already simplified, so adding a new `Expr`
+// variant only means adding an arm here rather than writing a new traversal.
+fn simplify(expr: &Expr, context: &mut Context) -> Result<Expr, String> {
+    let new_expr = expr.fold(&mut |node: ExprF<Result<Expr, String>>| simplify_node(node, context))?;
 
-    if expr != &new_expr  {
+    if expr != &new_expr {
         // println!("simplify {} => {}", expr, &new_expr);
     }
 
-    new_expr
+    Ok(new_expr)
+}
+
+fn simplify_node(node: ExprF<Result<Expr, String>>, context: &mut Context) -> Result<Expr, String> {
+    match node {
+        ExprF::BinaryExpr(lhs, Op::Add, rhs) => simplify_add(Expr::binary(lhs?, Op::Add, rhs?), context),
+        ExprF::BinaryExpr(lhs, Op::Multiply, rhs) => simplify_multiply(Expr::binary(lhs?, Op::Multiply, rhs?), context),
+        ExprF::BinaryExpr(a, Op::Exponent, b) => {
+            let a = a?;
+            let b = b?;
+
+            if let (&ExprF::Number(ref x), &ExprF::Number(ref y)) = (a.node(), b.node()) {
+                if let Some(n) = real_power(x, y) {
+                    return Ok(Expr::number(n));
+                }
+            }
+
+            Ok(Expr::binary(a, Op::Exponent, b))
+        },
+        ExprF::Application(head, args) => {
+            let head = head?;
+            let args: Vec<Expr> = args.into_iter().collect::<Result<_, _>>()?;
+            simplify_application(head, args, context)
+        },
+        ExprF::BinaryExpr(a, op, b) if is_comparison(op) => {
+            let a = a?;
+            let b = b?;
+
+            if let (&ExprF::Number(ref x), &ExprF::Number(ref y)) = (a.node(), b.node()) {
+                return Ok(Expr::boolean(compare(op, x, y)));
+            }
+
+            Ok(Expr::binary(a, op, b))
+        },
+        ExprF::BinaryExpr(a, op, b) if is_bitwise(op) => {
+            let a = a?;
+            let b = b?;
+
+            if let (&ExprF::Number(ref x), &ExprF::Number(ref y)) = (a.node(), b.node()) {
+                return bitwise(op, x, y).map(Expr::number);
+            }
+
+            Ok(Expr::binary(a, op, b))
+        },
+        ExprF::Number(n) => Ok(Expr::number(n)),
+        ExprF::Name(n) => match context.get(&n) {
+            // A name bound to a function stays a Name so `simplify_application`
+            // can still recognise the call; any other binding is substituted.
+            Some(value) => match value.node() {
+                &ExprF::Function(..) => Ok(Expr::name(n)),
+                _ => {
+                    let value = normalize(&value);
+                    let mut inner = context.evaluate(n);
+                    simplify(&value, &mut inner)
+                }
+            },
+            None => Ok(Expr::name(n))
+        },
+        ExprF::Boolean(b) => Ok(Expr::boolean(b)),
+        ExprF::Tuple(v) => Ok(Expr::tuple(v.into_iter().collect::<Result<_, _>>()?)),
+        ExprF::Assign(lhs, rhs) => Ok(Expr::assign(lhs?, rhs?)),
+        ExprF::Function(params, body) => Ok(Expr::function(params, body?)),
+        ExprF::BinaryExpr(lhs, op, rhs) => Ok(Expr::binary(lhs?, op, rhs?))
+    }
+}
+
+fn is_comparison(op: Op) -> bool {
+    match op {
+        Op::Equals | Op::NotEquals | Op::Less | Op::Greater | Op::LessEq | Op::GreaterEq => true,
+        _ => false
+    }
+}
+
+fn compare(op: Op, a: &BigRational, b: &BigRational) -> bool {
+    match op {
+        Op::Equals => a == b,
+        Op::NotEquals => a != b,
+        Op::Less => a < b,
+        Op::Greater => a > b,
+        Op::LessEq => a <= b,
+        Op::GreaterEq => a >= b,
+        _ => unreachable!()
+    }
+}
+
+fn is_bitwise(op: Op) -> bool {
+    match op {
+        Op::BitAnd | Op::BitOr | Op::BitXor => true,
+        _ => false
+    }
 }
 
-fn simplify_inner(expr: &Expr) -> Expr {
-    match *expr {
-        Expr::BinaryExpr(_, Op::Add, _) => simplify_add(expr),
-        Expr::BinaryExpr(_, Op::Multiply, _) => simplify_multiply(expr),
-        Expr::BinaryExpr(ref a, Op::Exponent, ref b) => {
-            let a = simplify(a);
-            let b = simplify(b);
-
-            if let Expr::Number(ref a) = a {
-                if let Expr::Number(ref b) = b {
-                    if let Some(n) = real_power(a, b) {
-                        return Expr::Number(n);
-                    }
+// Operates on the exact BigInt underlying each side; both operands must be
+// whole numbers (denominator 1). `num`'s `BigInt` has no bitwise impls of its
+// own (only the sign-less `BigUint` does), so negative operands are rejected
+// rather than inventing a two's-complement convention.
+fn bitwise(op: Op, a: &BigRational, b: &BigRational) -> Result<BigRational, String> {
+    if !a.is_integer() || !b.is_integer() {
+        return Err(format!("Cannot apply {} to non-integer values {} and {}", op, a, b));
+    }
+
+    let a = a.numer();
+    let b = b.numer();
+
+    let (a, b) = match (a.to_biguint(), b.to_biguint()) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return Err(format!("Cannot apply {} to negative values {} and {}", op, a, b))
+    };
+
+    let result = match op {
+        Op::BitAnd => a & b,
+        Op::BitOr => a | b,
+        Op::BitXor => a ^ b,
+        _ => unreachable!()
+    };
+
+    Ok(BigRational::from_integer(result.to_bigint().unwrap()))
+}
+
+// If `head` names a user-defined function in scope, substitute the (already
+// simplified) arguments into its body and simplify the result. If it names a
+// builtin and every argument is a number, the builtin is called. Otherwise the
+// call is left as a symbolic, unevaluated Application — e.g. `gcd(a, 6)`
+// before `a` is bound.
+fn simplify_application(head: Expr, args: Vec<Expr>, context: &mut Context) -> Result<Expr, String> {
+    if let &ExprF::Name(ref name) = head.node() {
+        if let Some(f) = context.get(name) {
+            if let &ExprF::Function(ref params, ref body) = f.node() {
+                if params.len() == args.len() {
+                    let substituted = normalize(&substitute(body, params, &args));
+                    let mut inner = context.evaluate(name.clone());
+                    return simplify(&substituted, &mut inner);
                 }
             }
+        }
+
+        let all_numeric = args.iter().all(|a| match a.node() {
+            &ExprF::Number(_) => true,
+            _ => false
+        });
+
+        if all_numeric {
+            if let Some(builtin) = context.builtin(name) {
+                return builtin(&args);
+            }
+        }
+    }
+
+    Ok(Expr::application(head, args))
+}
+
+// Replace every free occurrence of a parameter name with its argument.
+// `Name` and `Function` are the only variants that care about `params`/`args`
+// themselves (a lookup, and a shadowing filter, respectively); everything
+// else is routed through `ExprF::map` so a new variant only needs handling
+// here if it also binds names.
+fn substitute(expr: &Expr, params: &[String], args: &[Expr]) -> Expr {
+    match expr.node() {
+        &ExprF::Name(ref n) => {
+            match params.iter().position(|p| p == n) {
+                Some(i) => args[i].clone(),
+                None => expr.clone()
+            }
+        },
+        &ExprF::Function(ref inner_params, ref body) => {
+            // Don't substitute a parameter shadowed by the nested function's own.
+            let (rp, ra): (Vec<String>, Vec<Expr>) = params.iter().cloned().zip(args.iter().cloned())
+                .filter(|&(ref p, _)| !inner_params.contains(p))
+                .unzip();
 
-            Expr::BinaryExpr(Box::new(a), Op::Exponent, Box::new(b))
+            Expr::function(inner_params.clone(), substitute(body, &rp, &ra))
         },
-        _ => expr.clone()
+        node => Expr::new(node.clone().map(|child: Expr| substitute(&child, params, args)))
     }
 }
 
@@ -160,13 +286,23 @@ fn real_power(a: &BigRational, b: &BigRational) -> Option<BigRational> {
     None
 }
 
-fn simplify_add(expr: &Expr) -> Expr {
+fn simplify_add(expr: Expr, context: &mut Context) -> Result<Expr, String> {
     let mut items = Vec::new();
-    let mut current = expr.clone();
+    let mut current = expr;
+
+    loop {
+        let next = match current.into_node() {
+            ExprF::BinaryExpr(lhs, Op::Add, rhs) => {
+                items.push(lhs);
+                rhs
+            },
+            other => {
+                current = Expr::new(other);
+                break;
+            }
+        };
 
-    while let Expr::BinaryExpr(lhs, Op::Add, rhs) = current {
-        items.push(*lhs);
-        current = *rhs;
+        current = next;
     }
 
     items.push(current);
@@ -174,12 +310,12 @@ fn simplify_add(expr: &Expr) -> Expr {
     let mut coefficients = vec![BigRational::from_integer(FromPrimitive::from_u64(1).unwrap()); items.len()];
 
     for i in 0..items.len() {
-        let mut new = simplify(&items[i]);
+        let mut new = simplify(&items[i], context)?;
 
-        new = if let Expr::BinaryExpr(ref l, Op::Multiply, ref f) = new {
-            if let Expr::Number(ref c) = **l {
+        new = if let &ExprF::BinaryExpr(ref l, Op::Multiply, ref f) = new.node() {
+            if let &ExprF::Number(ref c) = l.node() {
                 coefficients[i] = c.clone();
-                f.as_ref().clone()
+                f.clone()
             } else {
                 new.clone()
             }
@@ -205,35 +341,34 @@ fn simplify_add(expr: &Expr) -> Expr {
     let mut sum = BigRational::zero();
 
     for (item, coeff) in items.iter().zip(coefficients) {
-        if let Expr::Number(ref n) = *item {
+        if let &ExprF::Number(ref n) = item.node() {
             sum = sum + coeff * n;
         } else if coeff == BigRational::one() {
             replacement.push(item.clone());
         } else if coeff != BigRational::zero() {
-            replacement.push(Expr::BinaryExpr(
-                Box::new(Expr::Number(coeff.clone())),
-                Op::Multiply, Box::new(item.clone())));
+            replacement.push(Expr::binary(
+                Expr::number(coeff.clone()),
+                Op::Multiply, item.clone()));
         }
     }
 
     if sum != BigRational::zero() {
-        replacement.insert(0, Expr::Number(sum));
+        replacement.insert(0, Expr::number(sum));
     }
 
     let mut result = match replacement.pop() {
         Some(e) => e,
-        None => Expr::Number(BigRational::zero())
+        None => Expr::number(BigRational::zero())
     };
 
     while let Some(next_result) = replacement.pop() {
-        result = Expr::BinaryExpr(Box::new(next_result),
-            Op::Add, Box::new(result));
+        result = Expr::binary(next_result, Op::Add, result);
     }
 
-    result
+    Ok(result)
 }
 
-fn simplify_multiply(expr: &Expr) -> Expr {
+fn simplify_multiply(expr: Expr, context: &mut Context) -> Result<Expr, String> {
     #[derive(Clone)]
     struct Term {
         coeff: BigRational,
@@ -242,31 +377,47 @@ fn simplify_multiply(expr: &Expr) -> Expr {
     }
 
     let mut items = Vec::new();
-    let mut current = expr.clone();
+    let mut current = expr;
+
+    loop {
+        let next = match current.into_node() {
+            ExprF::BinaryExpr(lhs, Op::Multiply, rhs) => {
+                items.push(lhs);
+                rhs
+            },
+            other => {
+                current = Expr::new(other);
+                break;
+            }
+        };
 
-    while let Expr::BinaryExpr(lhs, Op::Multiply, rhs) = current {
-        items.push(*lhs);
-        current = *rhs;
+        current = next;
     }
 
     items.push(current);
 
-    let mut terms: Vec<Term> = items.iter().map(|i| Term {
-        coeff: BigRational::one(),
-        base: simplify(i),
-        power: BigRational::one()}).collect();
+    let mut terms: Vec<Term> = Vec::with_capacity(items.len());
+
+    for i in items.iter() {
+        terms.push(Term {
+            coeff: BigRational::one(),
+            base: simplify(i, context)?,
+            power: BigRational::one()
+        });
+    }
+
     let mut coeff = BigRational::one();
 
     for term in &mut terms {
         let mut new = term.clone();
 
-        if let Expr::Number(ref n) = term.base {
+        if let &ExprF::Number(ref n) = term.base.node() {
             coeff = &coeff * n;
             new.coeff = BigRational::zero();
-        } else if let Expr::BinaryExpr(ref a, Op::Exponent, ref b) = term.base {
-            if let Expr::Number(ref n) = **b {
+        } else if let &ExprF::BinaryExpr(ref a, Op::Exponent, ref b) = term.base.node() {
+            if let &ExprF::Number(ref n) = b.node() {
                 new.power = n.clone();
-                new.base = (**a).clone();
+                new.base = a.clone();
             }
         }
 
@@ -292,37 +443,33 @@ fn simplify_multiply(expr: &Expr) -> Expr {
             let mut e = term.base.clone();
 
             if term.power != BigRational::one() {
-                e = Expr::BinaryExpr(Box::new(e), Op::Exponent,
-                    Box::new(Expr::Number(term.power.clone())));
+                e = Expr::binary(e, Op::Exponent, Expr::number(term.power.clone()));
             }
 
             if term.coeff == BigRational::one() {
                 replacement.push(e);
             } else if !term.coeff.is_zero() {
-                replacement.push(Expr::BinaryExpr(
-                    Box::new(Expr::Number(term.coeff.clone())),
-                    Op::Multiply, Box::new(e)));
+                replacement.push(Expr::binary(Expr::number(term.coeff.clone()), Op::Multiply, e));
             }
         }
     }
 
     if coeff == BigRational::zero() {
-        return Expr::Number(coeff);
+        return Ok(Expr::number(coeff));
     }
 
     if coeff != BigRational::one() {
-        replacement.insert(0, Expr::Number(coeff));
+        replacement.insert(0, Expr::number(coeff));
     }
 
     let mut result = match replacement.pop() {
         Some(e) => e,
-        None => Expr::Number(BigRational::one())
+        None => Expr::number(BigRational::one())
     };
 
     while let Some(next_result) = replacement.pop() {
-        result = Expr::BinaryExpr(Box::new(next_result),
-            Op::Multiply, Box::new(result));
+        result = Expr::binary(next_result, Op::Multiply, result);
     }
 
-    result
+    Ok(result)
 }
@@ -12,6 +12,8 @@ mod parser;
 mod tokenizer;
 mod evaluator;
 mod context;
+mod builtins;
+mod types;
 
 fn main() {
 	let mut scope = context::Scope::new();
@@ -53,16 +55,38 @@ fn input(line: &String, scope: &mut context::Scope, align: bool) {
 
     let mut context = context::Context::new(scope);
 
-    if let parser::Expr::Assign(lhs, rhs) = expression {
-        if let parser::Expr::Name(ref name) = *lhs {
-            context.insert((*name).clone(), *rhs);
-        } else {
-            println!("Error: Cannot assign to {}", lhs);
+    if let Err(e) = types::check(&expression, &mut context) {
+        println!("Type error: {}", e);
+        return;
+    }
+
+    if let &parser::ExprF::Assign(ref lhs, ref rhs) = expression.node() {
+        match lhs.node() {
+            &parser::ExprF::Name(ref name) => {
+                context.insert(name.clone(), rhs.clone());
+            },
+            &parser::ExprF::Application(ref head, ref args) => {
+                let params: Option<Vec<String>> = args.iter().map(|a| match a.node() {
+                    &parser::ExprF::Name(ref n) => Some(n.clone()),
+                    _ => None
+                }).collect();
+
+                match (head.node(), params) {
+                    (&parser::ExprF::Name(ref name), Some(params)) => {
+                        context.insert(name.clone(), parser::Expr::function(params, rhs.clone()));
+                    },
+                    _ => println!("Error: Cannot assign to {}", lhs)
+                }
+            },
+            _ => println!("Error: Cannot assign to {}", lhs)
         }
         return;
     }
 
-    let expression = evaluator::evaluate(expression, &mut context).ok().unwrap();
+    let expression = match evaluator::evaluate(expression, &mut context) {
+        Ok(e) => e,
+        Err(e) => { println!("Error: {}", e); return }
+    };
 
     if align {
         print!("  ");
@@ -71,7 +95,7 @@ fn input(line: &String, scope: &mut context::Scope, align: bool) {
     print!("{}", expression);
 
     // Print fraction of numbers
-    if let parser::Expr::Number(ref n) = expression {
+    if let &parser::ExprF::Number(ref n) = expression.node() {
         if !n.is_integer() {
             print!("  ({:#})", expression);
         }
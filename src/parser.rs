@@ -6,14 +6,74 @@ use std::iter::{Iterator, Peekable};
 use num::{Zero, Signed, Integer, BigRational};
 use num::bigint::{Sign, ToBigInt};
 
+// The node shape, parameterized over where its children live. `Expr` below ties
+// the knot (`R = Expr`) to get the real recursive tree; everything else (map,
+// fold, fold_with_context) stays generic so adding a variant only means adding
+// an arm to `map` instead of touching every hand-written traversal.
 #[derive(Debug, PartialEq, Clone)]
-pub enum Expr {
+pub enum ExprF<R> {
 	Number(BigRational),
 	Name(String),
 	Boolean(bool),
-	Tuple(Vec<Expr>),
-	Assign(Box<Expr>, Box<Expr>),
-	BinaryExpr(Box<Expr>, Op, Box<Expr>)
+	Tuple(Vec<R>),
+	Assign(R, R),
+	BinaryExpr(R, Op, R),
+	Application(R, Vec<R>),
+	Function(Vec<String>, R)
+}
+
+impl<R> ExprF<R> {
+	pub fn map<S, F: FnMut(R) -> S>(self, mut f: F) -> ExprF<S> {
+		use self::ExprF::*;
+
+		match self {
+			Number(n) => Number(n),
+			Name(n) => Name(n),
+			Boolean(b) => Boolean(b),
+			Tuple(v) => Tuple(v.into_iter().map(&mut f).collect()),
+			Assign(lhs, rhs) => Assign(f(lhs), f(rhs)),
+			BinaryExpr(lhs, op, rhs) => BinaryExpr(f(lhs), op, f(rhs)),
+			Application(head, args) => Application(f(head), args.into_iter().map(&mut f).collect()),
+			Function(params, body) => Function(params, f(body))
+		}
+	}
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Expr(pub Box<ExprF<Expr>>);
+
+impl Expr {
+	pub fn new(node: ExprF<Expr>) -> Expr { Expr(Box::new(node)) }
+	pub fn node(&self) -> &ExprF<Expr> { &self.0 }
+	pub fn into_node(self) -> ExprF<Expr> { *self.0 }
+
+	pub fn number(n: BigRational) -> Expr { Expr::new(ExprF::Number(n)) }
+	pub fn name(n: String) -> Expr { Expr::new(ExprF::Name(n)) }
+	pub fn boolean(b: bool) -> Expr { Expr::new(ExprF::Boolean(b)) }
+	pub fn tuple(v: Vec<Expr>) -> Expr { Expr::new(ExprF::Tuple(v)) }
+	pub fn assign(lhs: Expr, rhs: Expr) -> Expr { Expr::new(ExprF::Assign(lhs, rhs)) }
+	pub fn binary(lhs: Expr, op: Op, rhs: Expr) -> Expr { Expr::new(ExprF::BinaryExpr(lhs, op, rhs)) }
+	pub fn application(head: Expr, args: Vec<Expr>) -> Expr { Expr::new(ExprF::Application(head, args)) }
+	pub fn function(params: Vec<String>, body: Expr) -> Expr { Expr::new(ExprF::Function(params, body)) }
+
+	/// Catamorphism: children are folded to `T` before `f` combines them at this node.
+	pub fn fold<T>(&self, f: &mut impl FnMut(ExprF<T>) -> T) -> T {
+		let mapped = self.node().clone().map(|child: Expr| child.fold(f));
+		f(mapped)
+	}
+
+	/// Like `fold`, but each node also receives the operator its parent will
+	/// render it under. `Display` uses this to decide parenthesization without
+	/// every case of `simplify`/`normalize` having to know about it too.
+	pub fn fold_with_context<T>(&self, ctx: Option<Op>, f: &mut impl FnMut(&ExprF<Expr>, Option<Op>, ExprF<T>) -> T) -> T {
+		let node = self.node();
+		let child_ctx = match node {
+			&ExprF::BinaryExpr(_, op, _) => Some(op),
+			_ => None
+		};
+		let mapped = node.clone().map(|child: Expr| child.fold_with_context(child_ctx, f));
+		f(node, ctx, mapped)
+	}
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -25,24 +85,33 @@ pub enum Op {
 	Divide,
 	Modulus,
 	Exponent,
-	Equals
+	Equals,
+	NotEquals,
+	Less,
+	Greater,
+	LessEq,
+	GreaterEq,
+	BitAnd,
+	BitOr,
+	BitXor
 }
 
 fn op_prec(op: Op) -> u64 {
     use self::Op::*;
     match op {
-        Add => 2,
-        Subtract => 1,
-        Multiply => 4,
-        Adjacent => 4,
-        Divide => 3,
-        Modulus => 4,
-        Exponent => 5,
-        Equals => 6
+        Equals | NotEquals | Less | Greater | LessEq | GreaterEq => 0,
+        BitAnd | BitOr | BitXor => 1,
+        Subtract => 2,
+        Add => 3,
+        Divide => 4,
+        Multiply => 5,
+        Adjacent => 5,
+        Modulus => 5,
+        Exponent => 6
     }
 }
 
-fn needs_paren(e: &Expr, p: Option<Op>) -> bool {
+fn needs_paren<R>(e: &ExprF<R>, p: Option<Op>) -> bool {
     let p = match p {
         Some(p) => p,
         None => return false
@@ -50,115 +119,102 @@ fn needs_paren(e: &Expr, p: Option<Op>) -> bool {
 
     match *e {
         // needed to avoid confusing (a (-1)) with (a - 1)
-        Expr::Number(ref i) => i.is_negative() && p == Op::Adjacent,
-        Expr::BinaryExpr(_, ref o, _) => op_prec(*o) < op_prec(p),
+        ExprF::Number(ref i) => i.is_negative() && p == Op::Adjacent,
+        ExprF::BinaryExpr(_, ref o, _) => op_prec(*o) < op_prec(p),
         _ => false
     }
 }
 
-fn display_expr_parent(e: &Expr, f: &mut fmt::Formatter, p: Option<Op>) -> fmt::Result {
-    use std::fmt::Display;
-
-    let needs_paren = needs_paren(e, p);
-    // println!("needs_paren {:?} => {}", e, needs_paren);
+// Renders a node given its already-rendered children (`mapped`). `node` is kept
+// around purely so `needs_paren` can inspect the original shape (e.g. the sign
+// of a Number), since `map` only touches recursive slots.
+fn render_node(node: &ExprF<Expr>, ctx: Option<Op>, mapped: ExprF<String>, precision: Option<usize>, alternate: bool) -> String {
+    let wrap = needs_paren(node, ctx);
 
-    if needs_paren {
-        write!(f, "(")?;
-    }
-
-    match e {
-        &Expr::Number(ref i) => {
-            if f.alternate() {
-                i.fmt(f)
+    let body = match mapped {
+        ExprF::Number(ref i) => {
+            if alternate {
+                format!("{}", i)
             } else {
-                fmt_ratio_decimal(i, f)
+                fmt_ratio_decimal(i, precision)
             }
         },
-        &Expr::Name(ref n) => write!(f, "{}", n),
-        &Expr::Boolean(ref b) => write!(f, "{}", b),
-        &Expr::Tuple(ref v) => {
-            write!(f, "(")?;
+        ExprF::Name(ref n) => n.clone(),
+        ExprF::Boolean(ref b) => format!("{}", b),
+        ExprF::Tuple(ref v) => {
+            let mut s = String::from("(");
 
             let mut it = v.iter().peekable();
 
             if let Some(e) = it.next() {
-                e.fmt(f)?;
+                s.push_str(e);
 
                 if it.peek().is_none() {
-                    write!(f, ",")?;
+                    s.push_str(",");
                 }
             }
 
             for e in it {
-                write!(f, ",")?;
-                e.fmt(f)?;
+                s.push_str(",");
+                s.push_str(e);
             }
 
-            write!(f, ")")
-        },
-        &Expr::BinaryExpr(ref lhs, Op::Adjacent, ref rhs) => {
-            write!(f, "(")?;
-            lhs.fmt(f)?;
-            write!(f, " ")?;
-            rhs.fmt(f)?;
-            write!(f, ")")
-        },
-        &Expr::Assign(ref lhs, ref rhs) => {
-            write!(f, "(")?;
-            lhs.fmt(f)?;
-            write!(f, " ≔ ")?;
-            rhs.fmt(f)?;
-            write!(f, ")")
+            s.push_str(")");
+            s
         },
-        &Expr::BinaryExpr(ref lhs, ref op, ref rhs) => {
-            display_expr_parent(lhs.as_ref(), f, Some(*op))?;
-            write!(f, " ")?;
-            op.fmt(f)?;
-            write!(f, " ")?;
-            display_expr_parent(rhs.as_ref(), f, Some(*op))
-        }
-    }?;
-
-    if needs_paren {
-        write!(f, ")")
-    } else {
-        Ok(())
-    }
+        ExprF::BinaryExpr(ref lhs, Op::Adjacent, ref rhs) => format!("({} {})", lhs, rhs),
+        ExprF::Assign(ref lhs, ref rhs) => format!("({} ≔ {})", lhs, rhs),
+        ExprF::BinaryExpr(ref lhs, ref op, ref rhs) => format!("{} {} {}", lhs, op, rhs),
+        ExprF::Application(ref head, ref args) => format!("{}({})", head, args.join(", ")),
+        ExprF::Function(ref params, ref body) => format!("({}) ≔ {}", params.join(", "), body)
+    };
+
+    if wrap { format!("({})", body) } else { body }
 }
 
 impl fmt::Display for Expr {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        display_expr_parent(self, f, None)
+		let precision = f.precision();
+		let alternate = f.alternate();
+
+		let rendered = self.fold_with_context(None, &mut |node: &ExprF<Expr>, ctx: Option<Op>, mapped: ExprF<String>| {
+			render_node(node, ctx, mapped, precision, alternate)
+		});
+
+		write!(f, "{}", rendered)
 	}
 }
 
-fn fmt_ratio_decimal(r: &BigRational, f: &mut fmt::Formatter) -> fmt::Result {
-	let precision = f.precision().unwrap_or(5);
+fn fmt_ratio_decimal(r: &BigRational, precision: Option<usize>) -> String {
+	use std::fmt::Write;
+
+	let precision = precision.unwrap_or(5);
 	let base = 10.to_bigint().unwrap();
+	let mut s = String::new();
 
 	let num = r.numer();
 	let den = r.denom();
 	if num.sign() == Sign::Minus {
-		write!(f, "-")?;
+		s.push('-');
 	}
 
 	let mut div = num.abs().div_rem(den);
-	write!(f, "{}", div.0)?;
+	write!(s, "{}", div.0).unwrap();
 	if !div.1.is_zero() {
-		write!(f, ".")?;
+		s.push('.');
 	}
 
 	for _ in 0..precision {
 		if div.1.is_zero() { break }
 		div = (&base * div.1).div_rem(den);
-		write!(f, "{}", div.0)?;
+		write!(s, "{}", div.0).unwrap();
 	}
 
 	if !div.1.is_zero() {
-		write!(f, "⋯")
-	} else {
-		Ok(())
+		s.push_str("⋯");
 	}
+
+	s
 }
 
 impl fmt::Display for Op {
@@ -172,6 +228,14 @@ impl fmt::Display for Op {
 			&Op::Modulus => write!(f, "%"),
 			&Op::Exponent => write!(f, "^"),
 			&Op::Equals => write!(f, "="),
+			&Op::NotEquals => write!(f, "≠"),
+			&Op::Less => write!(f, "<"),
+			&Op::Greater => write!(f, ">"),
+			&Op::LessEq => write!(f, "≤"),
+			&Op::GreaterEq => write!(f, "≥"),
+			&Op::BitAnd => write!(f, "&"),
+			&Op::BitOr => write!(f, "|"),
+			&Op::BitXor => write!(f, "⊻"),
 		}
 	}
 }
@@ -180,7 +244,10 @@ const UNARY_PRIORITY: u8 = 8;
 
 fn get_precedence(token: &Token) -> Option<(u8, u8)> {
 	match token {
-		&Token::Equals => Some((3, 3)),
+		&Token::Equals | &Token::NotEquals
+			| &Token::Less | &Token::Greater
+			| &Token::LessEq | &Token::GreaterEq => Some((3, 3)),
+		&Token::Amper | &Token::Pipe | &Token::Xor => Some((4, 4)),
 		&Token::Add | &Token::Subtract => Some((6, 6)),
 		&Token::Multiply | &Token::Divide | &Token::Modulus => Some((7, 7)),
 		&Token::Exponent => Some((10, 9)),
@@ -192,7 +259,7 @@ pub fn parse(tokens: Vec<Token>) -> Result<Expr, String> {
 	let mut it = tokens.iter().peekable();
 
 	if it.peek().is_none() {
-		return Ok(Expr::Tuple(vec![]));
+		return Ok(Expr::tuple(vec![]));
 	}
 
 	let lhs = parse_expr(&mut it, 0)?;
@@ -201,7 +268,7 @@ pub fn parse(tokens: Vec<Token>) -> Result<Expr, String> {
 	if let Some(t) = next {
 		if let &Token::Assign = t {
 			let rhs = parse_expr(&mut it, 0)?;
-			Ok(Expr::Assign(Box::new(lhs), Box::new(rhs)))
+			Ok(Expr::assign(lhs, rhs))
 		} else {
 			Err(format!("Unexpected token: {:?}", t))
 		}
@@ -219,10 +286,10 @@ fn parse_expr<'a, It>(it: &mut Peekable<It>, precedence: u8) -> Result<Expr, Str
 		match next_token {
 			&Token::RightParen => break,
 			&Token::Name(_) | &Token::Number(_) | &Token::LeftParen => {
-				expr = Expr::BinaryExpr(
-					Box::new(expr),
+				expr = Expr::binary(
+					expr,
 					Op::Adjacent,
-					Box::new(parse_expr(it, 0)?) // FIXME: Is 0 the right precedence for this?
+					parse_expr(it, 0)? // FIXME: Is 0 the right precedence for this?
 				);
 				continue; // FIXME: Continue? Shouldn't this consume everything possible?
 			},
@@ -250,23 +317,46 @@ fn parse_prefix<'a, It>(it: &mut Peekable<It>) -> Result<Expr, String>
 	match it.next() {
 		Some(t) => match t {
 			&Token::Number(ref n) => {
-				Ok(Expr::Number(n.clone()))
+				Ok(Expr::number(n.clone()))
 			},
 			&Token::Name(ref n) => {
-				Ok(Expr::Name(n.clone()))
+				if let Some(&&Token::LeftParen) = it.peek() {
+					it.next().unwrap();
+
+					let mut args: Vec<Expr> = vec![];
+
+					if let Some(&&Token::RightParen) = it.peek() {
+						it.next().unwrap();
+						return Ok(Expr::application(Expr::name(n.clone()), args));
+					}
+
+					loop {
+						args.push(parse_expr(it, 0)?);
+
+						match it.next() {
+							Some(&Token::RightParen) => break,
+							Some(&Token::Comma) => {},
+							_ => return Err(String::from("Missing right parenthesis"))
+						}
+					}
+
+					Ok(Expr::application(Expr::name(n.clone()), args))
+				} else {
+					Ok(Expr::name(n.clone()))
+				}
 			},
 			&Token::Subtract => {
-				Ok(Expr::BinaryExpr(
-					Box::new(Expr::Number(BigRational::zero())),
+				Ok(Expr::binary(
+					Expr::number(BigRational::zero()),
 					Op::Subtract,
-					Box::new(parse_expr(it, UNARY_PRIORITY)?)))
+					parse_expr(it, UNARY_PRIORITY)?))
 			},
 			&Token::LeftParen => {
 				let mut exprs: Vec<Expr> = vec![];
 
 				if let Some(&&Token::RightParen) = it.peek() {
 					it.next().unwrap();
-					return Ok(Expr::Tuple(exprs));
+					return Ok(Expr::tuple(exprs));
 				}
 
 				loop {
@@ -282,7 +372,7 @@ fn parse_prefix<'a, It>(it: &mut Peekable<It>) -> Result<Expr, String>
 				if exprs.len() == 1 {
 					Ok(exprs.swap_remove(0))
 				} else {
-					Ok(Expr::Tuple(exprs))
+					Ok(Expr::tuple(exprs))
 				}
 			},
 			_ => Err(format!("Unexpected token: {:?}", t))
@@ -304,15 +394,20 @@ fn parse_infix<'a, It>(left: Expr, it: &mut Peekable<It>, precedence: u8) -> Res
 				&Token::Modulus => Op::Modulus,
 				&Token::Exponent => Op::Exponent,
 				&Token::Equals => Op::Equals,
+				&Token::NotEquals => Op::NotEquals,
+				&Token::Less => Op::Less,
+				&Token::Greater => Op::Greater,
+				&Token::LessEq => Op::LessEq,
+				&Token::GreaterEq => Op::GreaterEq,
+				&Token::Amper => Op::BitAnd,
+				&Token::Pipe => Op::BitOr,
+				&Token::Xor => Op::BitXor,
 				_ => return Err(format!("Unexpected token: {:?}", t))
 			};
 
 			let right = parse_expr(it, precedence)?;
 
-			Ok(Expr::BinaryExpr(
-				Box::new(left),
-				op,
-				Box::new(right)))
+			Ok(Expr::binary(left, op, right))
 		},
 		None => Err(String::from("No more tokens"))
 	}
@@ -17,6 +17,14 @@ pub enum Token {
 	Modulus,
 	Exponent,
 	Equals,
+	NotEquals,
+	Less,
+	Greater,
+	LessEq,
+	GreaterEq,
+	Amper,
+	Pipe,
+	Xor,
 	Comma,
 	Assign,
 }
@@ -29,10 +37,36 @@ pub fn tokenize(src: &String) -> Result<Vec<Token>, String> {
 		match it.peek() {
 			Some(&ch) => match ch {
 				'0' ... '9' | '.' => {
-					let num: Vec<char> = consume_while(&mut it, |a| a.is_numeric() || a == '_' || a == '.')
-						.into_iter()
-						.collect();
-					tokens.push(Token::Number(parse_number(num)?));
+					let radix = if ch == '0' {
+						let mut lookahead = it.clone();
+						lookahead.next();
+						match lookahead.peek() {
+							Some(&'x') | Some(&'X') => Some(16),
+							Some(&'b') | Some(&'B') => Some(2),
+							Some(&'o') | Some(&'O') => Some(8),
+							_ => None
+						}
+					} else {
+						None
+					};
+
+					if let Some(radix) = radix {
+						it.next().unwrap();
+						it.next().unwrap();
+
+						let digits = consume_while(&mut it, |a| a.is_digit(radix) || a == '_');
+
+						if let Some(&'.') = it.peek() {
+							return Err(String::from("Radix literals cannot have a decimal point"));
+						}
+
+						tokens.push(Token::Number(parse_radix_number(digits, radix)?));
+					} else {
+						let num: Vec<char> = consume_while(&mut it, |a| a.is_numeric() || a == '_' || a == '.')
+							.into_iter()
+							.collect();
+						tokens.push(Token::Number(parse_number(num)?));
+					}
 				},
 				'+' => {
 					it.next().unwrap();
@@ -62,6 +96,57 @@ pub fn tokenize(src: &String) -> Result<Vec<Token>, String> {
 					it.next().unwrap();
 					tokens.push(Token::Equals);
 				},
+				'!' => {
+					it.next().unwrap();
+					if let Some(&'=') = it.peek() {
+						it.next().unwrap();
+						tokens.push(Token::NotEquals);
+					} else {
+						return Err(String::from("Expected = after !"));
+					}
+				},
+				'≠' => {
+					it.next().unwrap();
+					tokens.push(Token::NotEquals);
+				},
+				'<' => {
+					it.next().unwrap();
+					if let Some(&'=') = it.peek() {
+						it.next().unwrap();
+						tokens.push(Token::LessEq);
+					} else {
+						tokens.push(Token::Less);
+					}
+				},
+				'>' => {
+					it.next().unwrap();
+					if let Some(&'=') = it.peek() {
+						it.next().unwrap();
+						tokens.push(Token::GreaterEq);
+					} else {
+						tokens.push(Token::Greater);
+					}
+				},
+				'≤' => {
+					it.next().unwrap();
+					tokens.push(Token::LessEq);
+				},
+				'≥' => {
+					it.next().unwrap();
+					tokens.push(Token::GreaterEq);
+				},
+				'&' => {
+					it.next().unwrap();
+					tokens.push(Token::Amper);
+				},
+				'|' => {
+					it.next().unwrap();
+					tokens.push(Token::Pipe);
+				},
+				'⊻' => {
+					it.next().unwrap();
+					tokens.push(Token::Xor);
+				},
 				':' => {
 					it.next().unwrap();
 					if let Some(&'=') = it.peek() {
@@ -126,6 +211,19 @@ fn parse_number(chars: Vec<char>) -> Result<BigRational, String> {
 	}
 }
 
+fn parse_radix_number(digits: Vec<char>, radix: u32) -> Result<BigRational, String> {
+	if digits.is_empty() {
+		return Err(format!("Expected at least one digit after base-{} prefix", radix));
+	}
+
+	let digits: String = digits.into_iter().filter(|&c| c != '_').collect();
+
+	match BigInt::parse_bytes(digits.as_bytes(), radix) {
+		Some(n) => Ok(BigRational::from_integer(n)),
+		None => Err(format!("Invalid base-{} literal: {}", radix, digits))
+	}
+}
+
 fn consume_while<F>(it: &mut Peekable<Chars>, x: F) -> Vec<char>
 	where F : Fn(char) -> bool {
 
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::parser::{Expr, ExprF, Op};
+use super::context::Context;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+	Num,
+	Bool,
+	Tuple(Vec<Type>),
+	Fun(Vec<Type>, Box<Type>),
+	Var(u32)
+}
+
+impl fmt::Display for Type {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			&Type::Num => write!(f, "Num"),
+			&Type::Bool => write!(f, "Bool"),
+			&Type::Tuple(ref items) => {
+				write!(f, "(")?;
+
+				for (i, t) in items.iter().enumerate() {
+					if i > 0 { write!(f, ", ")?; }
+					write!(f, "{}", t)?;
+				}
+
+				write!(f, ")")
+			},
+			&Type::Fun(ref params, ref ret) => {
+				write!(f, "(")?;
+
+				for (i, t) in params.iter().enumerate() {
+					if i > 0 { write!(f, ", ")?; }
+					write!(f, "{}", t)?;
+				}
+
+				write!(f, ") -> {}", ret)
+			},
+			&Type::Var(v) => write!(f, "t{}", v)
+		}
+	}
+}
+
+// Names not shadowed by a local (function-parameter) binding are typed by
+// re-inferring their stored `Expr` from `Context` each time they're used (in
+// the same `State`), which is a cheap stand-in for let-polymorphism: each use
+// site gets its own fresh type variables instead of sharing one instantiation.
+struct State {
+	next_var: u32,
+	subst: HashMap<u32, Type>,
+	frees: HashMap<String, Type>
+}
+
+impl State {
+	fn new() -> Self {
+		State { next_var: 0, subst: HashMap::new(), frees: HashMap::new() }
+	}
+
+	fn fresh(&mut self) -> Type {
+		let v = self.next_var;
+		self.next_var += 1;
+		Type::Var(v)
+	}
+
+	// A name bound nowhere (not a local, not in `Context`) is a free symbolic
+	// variable like the `a` in `gcd(a, 6)`; give it a fresh type variable
+	// instead of erroring, and reuse the same variable for repeat occurrences
+	// within this `check()` call so e.g. `x + x` still unifies `x` with itself.
+	fn fresh_for_name(&mut self, name: &str) -> Type {
+		if let Some(ty) = self.frees.get(name) {
+			return ty.clone();
+		}
+
+		let ty = self.fresh();
+		self.frees.insert(name.to_string(), ty.clone());
+		ty
+	}
+
+	fn resolve(&self, ty: &Type) -> Type {
+		match ty {
+			&Type::Var(v) => match self.subst.get(&v) {
+				Some(t) => self.resolve(t),
+				None => Type::Var(v)
+			},
+			&Type::Tuple(ref items) => Type::Tuple(items.iter().map(|t| self.resolve(t)).collect()),
+			&Type::Fun(ref params, ref ret) => Type::Fun(
+				params.iter().map(|t| self.resolve(t)).collect(),
+				Box::new(self.resolve(ret))),
+			other => other.clone()
+		}
+	}
+
+	fn occurs(&self, v: u32, ty: &Type) -> bool {
+		match self.resolve(ty) {
+			Type::Var(v2) => v2 == v,
+			Type::Tuple(items) => items.iter().any(|t| self.occurs(v, t)),
+			Type::Fun(params, ret) => params.iter().any(|t| self.occurs(v, t)) || self.occurs(v, &ret),
+			_ => false
+		}
+	}
+
+	fn unify(&mut self, a: &Type, b: &Type) -> Result<(), String> {
+		let a = self.resolve(a);
+		let b = self.resolve(b);
+
+		match (&a, &b) {
+			(&Type::Var(v1), &Type::Var(v2)) if v1 == v2 => Ok(()),
+			(&Type::Var(v), _) => {
+				if self.occurs(v, &b) {
+					Err(format!("Infinite type: t{} occurs in {}", v, b))
+				} else {
+					self.subst.insert(v, b);
+					Ok(())
+				}
+			},
+			(_, &Type::Var(v)) => {
+				if self.occurs(v, &a) {
+					Err(format!("Infinite type: t{} occurs in {}", v, a))
+				} else {
+					self.subst.insert(v, a);
+					Ok(())
+				}
+			},
+			(&Type::Num, &Type::Num) | (&Type::Bool, &Type::Bool) => Ok(()),
+			(&Type::Tuple(ref xs), &Type::Tuple(ref ys)) if xs.len() == ys.len() => {
+				for (x, y) in xs.iter().zip(ys) {
+					self.unify(x, y)?;
+				}
+				Ok(())
+			},
+			(&Type::Fun(ref xp, ref xr), &Type::Fun(ref yp, ref yr)) if xp.len() == yp.len() => {
+				for (x, y) in xp.iter().zip(yp) {
+					self.unify(x, y)?;
+				}
+				self.unify(xr, yr)
+			},
+			_ => Err(format!("expected {}, found {}", a, b))
+		}
+	}
+}
+
+/// Infers a type for `expr`, consulting `context` for free names, and reports
+/// a readable error on the first mismatch instead of evaluating.
+pub fn check(expr: &Expr, context: &mut Context) -> Result<Type, String> {
+	let mut state = State::new();
+	let env = HashMap::new();
+	let ty = infer(expr, &env, context, &mut state)?;
+	Ok(state.resolve(&ty))
+}
+
+// Not an `Expr::fold`: unlike `simplify`/`normalize`/`Display`, each variant
+// here needs a *different* child environment (`Function` extends `env` with
+// its params, `Assign` binds a letrec name before descending) rather than
+// the same one passed straight down, so the children can't be folded to
+// `Type` up front the way `fold`/`fold_with_context` assume. A `Result` per
+// node also needs to short-circuit on the first error, which doesn't fit a
+// plain `FnMut(ExprF<T>) -> T` combinator either.
+fn infer(expr: &Expr, env: &HashMap<String, Type>, context: &mut Context, state: &mut State) -> Result<Type, String> {
+	match expr.node() {
+		&ExprF::Number(_) => Ok(Type::Num),
+		&ExprF::Boolean(_) => Ok(Type::Bool),
+		&ExprF::Name(ref name) => infer_name(name, env, context, state),
+		&ExprF::Tuple(ref items) => {
+			let types = items.iter().map(|e| infer(e, env, context, state)).collect::<Result<_, _>>()?;
+			Ok(Type::Tuple(types))
+		},
+		&ExprF::Assign(ref lhs, ref rhs) => infer_assign(lhs, rhs, env, context, state),
+		&ExprF::BinaryExpr(ref lhs, op, ref rhs) => infer_binary(lhs, op, rhs, env, context, state),
+		&ExprF::Application(ref head, ref args) => infer_application(head, args, env, context, state),
+		&ExprF::Function(ref params, ref body) => {
+			let param_types: Vec<Type> = params.iter().map(|_| state.fresh()).collect();
+			let mut inner_env = env.clone();
+
+			for (p, t) in params.iter().zip(&param_types) {
+				inner_env.insert(p.clone(), t.clone());
+			}
+
+			let body_ty = infer(body, &inner_env, context, state)?;
+			Ok(Type::Fun(param_types, Box::new(body_ty)))
+		}
+	}
+}
+
+fn infer_name(name: &String, env: &HashMap<String, Type>, context: &mut Context, state: &mut State) -> Result<Type, String> {
+	if let Some(ty) = env.get(name) {
+		return Ok(ty.clone());
+	}
+
+	match context.get(name) {
+		Some(bound) => {
+			let mut inner = context.evaluate(name.clone());
+			infer(&bound, &HashMap::new(), &mut inner, state)
+		},
+		// Unbound outside of any scope: stay symbolic, like the evaluator
+		// does for `Expr::Name`, rather than rejecting `x + 1` before `x` is
+		// ever assigned.
+		None => Ok(state.fresh_for_name(name))
+	}
+}
+
+// Mirrors `main::input`'s own treatment of `Assign`: a plain `Name := rhs` is
+// just typed as `rhs`, while `f(params...) := rhs` is type-checked as though
+// it were the `Function` it will be turned into on insertion.
+fn infer_assign(lhs: &Expr, rhs: &Expr, env: &HashMap<String, Type>, context: &mut Context, state: &mut State) -> Result<Type, String> {
+	match lhs.node() {
+		&ExprF::Application(ref head, ref args) => {
+			let params: Option<Vec<String>> = args.iter().map(|a| match a.node() {
+				&ExprF::Name(ref n) => Some(n.clone()),
+				_ => None
+			}).collect();
+
+			match (head.node(), params) {
+				(&ExprF::Name(ref name), Some(params)) => {
+					// letrec: bind the function's own name to a fresh type variable
+					// before inferring its body, so self-recursive definitions like
+					// `fact(n) := n * fact(n - 1)` see themselves in `env` instead of
+					// looking unbound.
+					let self_ty = state.fresh();
+					let mut inner_env = env.clone();
+					inner_env.insert(name.clone(), self_ty.clone());
+
+					let actual = infer(&Expr::function(params, rhs.clone()), &inner_env, context, state)?;
+					state.unify(&self_ty, &actual)?;
+					Ok(self_ty)
+				},
+				_ => Err(format!("Cannot assign to {}", lhs))
+			}
+		},
+		_ => infer(rhs, env, context, state)
+	}
+}
+
+fn infer_binary(lhs: &Expr, op: Op, rhs: &Expr, env: &HashMap<String, Type>, context: &mut Context, state: &mut State) -> Result<Type, String> {
+	let lhs_ty = infer(lhs, env, context, state)?;
+	let rhs_ty = infer(rhs, env, context, state)?;
+
+	match op {
+		Op::Equals | Op::NotEquals => {
+			state.unify(&lhs_ty, &rhs_ty)?;
+			Ok(Type::Bool)
+		},
+		Op::Less | Op::Greater | Op::LessEq | Op::GreaterEq => {
+			state.unify(&lhs_ty, &Type::Num)?;
+			state.unify(&rhs_ty, &Type::Num)?;
+			Ok(Type::Bool)
+		},
+		_ => {
+			state.unify(&lhs_ty, &Type::Num)?;
+			state.unify(&rhs_ty, &Type::Num)?;
+			Ok(Type::Num)
+		}
+	}
+}
+
+fn infer_application(head: &Expr, args: &Vec<Expr>, env: &HashMap<String, Type>, context: &mut Context, state: &mut State) -> Result<Type, String> {
+	let arg_types: Vec<Type> = args.iter().map(|a| infer(a, env, context, state)).collect::<Result<_, _>>()?;
+
+	if let &ExprF::Name(ref name) = head.node() {
+		if env.get(name).is_none() && context.get(name).is_none() && context.builtin(name).is_some() {
+			for t in &arg_types {
+				state.unify(t, &Type::Num)?;
+			}
+
+			return Ok(Type::Num);
+		}
+	}
+
+	let head_ty = infer(head, env, context, state)?;
+	let ret = state.fresh();
+	state.unify(&head_ty, &Type::Fun(arg_types, Box::new(ret.clone())))?;
+	Ok(state.resolve(&ret))
+}